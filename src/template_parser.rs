@@ -12,13 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt;
+use std::ops::Range;
+
+use chrono::{FixedOffset, TimeZone, Utc};
 use itertools::Itertools as _;
 use jujutsu_lib::backend::{Signature, Timestamp};
 use jujutsu_lib::commit::Commit;
 use jujutsu_lib::op_store::WorkspaceId;
 use jujutsu_lib::repo::RepoRef;
 use pest::iterators::{Pair, Pairs};
-use pest::Parser;
+use pest::{Parser, Span};
 use pest_derive::Parser;
 
 use crate::formatter::PlainTextFormatter;
@@ -36,7 +40,162 @@ use crate::time_util;
 #[grammar = "template.pest"]
 pub struct TemplateParser;
 
-fn parse_string_literal(pair: Pair<Rule>) -> String {
+/// Error that points at the offending token in the template string.
+///
+/// The byte `span` is retained so callers can map the failure back to their
+/// own source, while `diagnostic` holds a ready-to-print, caret-underlined
+/// rendering produced from the pest span at the point of failure.
+#[derive(Clone, Debug)]
+pub struct TemplateError {
+    message: String,
+    span: Range<usize>,
+    diagnostic: String,
+}
+
+impl TemplateError {
+    fn new(span: Span, message: impl Into<String>) -> Self {
+        let message = message.into();
+        let diagnostic = pest::error::Error::<Rule>::new_from_span(
+            pest::error::ErrorVariant::CustomError {
+                message: message.clone(),
+            },
+            span.clone(),
+        )
+        .to_string();
+        TemplateError {
+            message,
+            span: span.start()..span.end(),
+            diagnostic,
+        }
+    }
+
+    fn with_pair(pair: &Pair<Rule>, message: impl Into<String>) -> Self {
+        TemplateError::new(pair.as_span(), message)
+    }
+
+    /// Human-readable message without positional information.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Byte range of the offending token within the template string.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.diagnostic)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+impl From<pest::error::Error<Rule>> for TemplateError {
+    fn from(err: pest::error::Error<Rule>) -> Self {
+        let span = match err.location {
+            pest::error::InputLocation::Pos(pos) => pos..pos,
+            pest::error::InputLocation::Span((start, end)) => start..end,
+        };
+        TemplateError {
+            message: err.variant.message().into_owned(),
+            span,
+            diagnostic: err.to_string(),
+        }
+    }
+}
+
+type TemplateResult<T> = Result<T, TemplateError>;
+
+/// Collects the argument expressions of a method call, erroring unless exactly
+/// `arity` were supplied.
+fn expect_arguments(
+    name: &Pair<Rule>,
+    args: Pairs<Rule>,
+    arity: usize,
+) -> TemplateResult<Vec<Pair<Rule>>> {
+    let args = args.collect_vec();
+    if args.len() != arity {
+        return Err(TemplateError::with_pair(
+            name,
+            format!(
+                "method `{}` expects {arity} argument(s), but got {}",
+                name.as_str(),
+                args.len()
+            ),
+        ));
+    }
+    Ok(args)
+}
+
+fn expect_no_arguments(name: &Pair<Rule>, args: Pairs<Rule>) -> TemplateResult<()> {
+    expect_arguments(name, args, 0).map(|_| ())
+}
+
+/// Descends into an argument `template` and returns its single primary token if
+/// the argument is a bare literal (no concatenation, operators, or methods).
+///
+/// Method arguments are deliberately restricted to literals rather than being
+/// evaluated through [`parse_commit_template_rule`]: the `parse_*_method`
+/// helpers run without a [`RepoRef`], so an argument that derived its value
+/// from a keyword or a nested method could not be resolved at parse time. A
+/// non-literal argument therefore yields `None` here and is reported as a type
+/// error by the caller instead of being silently accepted. In practice this
+/// means a computed argument such as `substr(len(), 2)` is rejected rather than
+/// evaluated; only bare `String`/integer literals are accepted.
+fn unwrap_literal_argument(arg: &Pair<Rule>) -> Option<Pair<Rule>> {
+    debug_assert_eq!(arg.as_rule(), Rule::template);
+    let mut parts = arg.clone().into_inner();
+    let not_expr = parts.next()?;
+    if parts.next().is_some() || not_expr.as_rule() != Rule::not_expr {
+        return None; // an operator expression, not a literal
+    }
+    let list = not_expr.into_inner().next()?;
+    if list.as_rule() != Rule::list {
+        return None; // a `!` prefix is applied to the argument
+    }
+    let mut terms = list.into_inner();
+    let term = terms.next()?;
+    if terms.next().is_some() {
+        return None; // concatenation of several terms
+    }
+    let mut inner = term.into_inner();
+    let primary = inner.next()?;
+    if let Some(maybe_method) = inner.next() {
+        if maybe_method.into_inner().next().is_some() {
+            return None; // a method is chained onto the literal
+        }
+    }
+    primary.into_inner().next()
+}
+
+fn parse_string_argument(arg: Pair<Rule>) -> TemplateResult<String> {
+    let span = arg.as_span();
+    match unwrap_literal_argument(&arg) {
+        Some(pair) if pair.as_rule() == Rule::literal => parse_string_literal(pair),
+        _ => Err(TemplateError::new(
+            span,
+            "expected a string literal argument (method arguments must be literals)",
+        )),
+    }
+}
+
+fn parse_integer_argument(arg: Pair<Rule>) -> TemplateResult<i64> {
+    let span = arg.as_span();
+    match unwrap_literal_argument(&arg) {
+        Some(pair) if pair.as_rule() == Rule::integer_literal => pair
+            .as_str()
+            .parse()
+            .map_err(|_| TemplateError::new(span, "integer argument is out of range")),
+        _ => Err(TemplateError::new(
+            span,
+            "expected an integer argument (method arguments must be literals)",
+        )),
+    }
+}
+
+fn parse_string_literal(pair: Pair<Rule>) -> TemplateResult<String> {
     assert_eq!(pair.as_rule(), Rule::literal);
     let mut result = String::new();
     for part in pair.into_inner() {
@@ -48,12 +207,17 @@ fn parse_string_literal(pair: Pair<Rule>) -> String {
                 '"' => result.push('"'),
                 '\\' => result.push('\\'),
                 'n' => result.push('\n'),
-                char => panic!("invalid escape: \\{char:?}"),
+                char => {
+                    return Err(TemplateError::with_pair(
+                        &part,
+                        format!("invalid escape: \\{char}"),
+                    ))
+                }
             },
-            _ => panic!("unexpected part of string: {part:?}"),
+            _ => return Err(TemplateError::with_pair(&part, "unexpected part of string")),
         }
     }
-    result
+    Ok(result)
 }
 
 enum Property<'a, I> {
@@ -63,6 +227,7 @@ enum Property<'a, I> {
     IdWithHighlightedPrefix(Box<dyn TemplateProperty<I, Output = IdWithHighlightedPrefix> + 'a>),
     Signature(Box<dyn TemplateProperty<I, Output = Signature> + 'a>),
     Timestamp(Box<dyn TemplateProperty<I, Output = Timestamp> + 'a>),
+    Integer(Box<dyn TemplateProperty<I, Output = i64> + 'a>),
 }
 
 impl<'a, I: 'a> Property<'a, I> {
@@ -86,6 +251,7 @@ impl<'a, I: 'a> Property<'a, I> {
             }
             Property::Signature(property) => Property::Signature(chain(first, property)),
             Property::Timestamp(property) => Property::Timestamp(chain(first, property)),
+            Property::Integer(property) => Property::Integer(chain(first, property)),
         }
     }
 
@@ -95,6 +261,9 @@ impl<'a, I: 'a> Property<'a, I> {
                 Some(Box::new(TemplateFunction::new(property, |s| !s.is_empty())))
             }
             Property::Boolean(property) => Some(property),
+            Property::Integer(property) => {
+                Some(Box::new(TemplateFunction::new(property, |i| i != 0)))
+            }
             _ => None,
         }
     }
@@ -112,6 +281,11 @@ impl<'a, I: 'a> Property<'a, I> {
             Property::IdWithHighlightedPrefix(property) => wrap(property),
             Property::Signature(property) => wrap(property),
             Property::Timestamp(property) => wrap(property),
+            // i64 isn't formattable on its own, so render it through its
+            // decimal string representation.
+            Property::Integer(property) => {
+                wrap(Box::new(TemplateFunction::new(property, |i| i.to_string())))
+            }
         }
     }
 }
@@ -142,6 +316,13 @@ impl<'a, C: 'a> Expression<'a, C> {
         }
     }
 
+    fn try_into_property(self) -> Option<Property<'a, C>> {
+        match self {
+            Expression::Property(PropertyAndLabels(property, _)) => Some(property),
+            Expression::Template(_) => None,
+        }
+    }
+
     fn into_template(self) -> Box<dyn Template<C> + 'a> {
         match self {
             Expression::Property(property_labels) => property_labels.into_template(),
@@ -150,10 +331,17 @@ impl<'a, C: 'a> Expression<'a, C> {
     }
 }
 
+/// Wraps a boolean property into a label-less [`Expression`].
+fn boolean_expression<'a, C: 'a>(
+    property: Box<dyn TemplateProperty<C, Output = bool> + 'a>,
+) -> Expression<'a, C> {
+    Expression::Property(PropertyAndLabels(Property::Boolean(property), vec![]))
+}
+
 fn parse_method_chain<'a, I: 'a>(
     pair: Pair<Rule>,
     input_property: PropertyAndLabels<'a, I>,
-) -> PropertyAndLabels<'a, I> {
+) -> TemplateResult<PropertyAndLabels<'a, I>> {
     let PropertyAndLabels(mut property, mut labels) = input_property;
     assert_eq!(pair.as_rule(), Rule::maybe_method);
     for chain in pair.into_inner() {
@@ -168,50 +356,113 @@ fn parse_method_chain<'a, I: 'a>(
         };
         labels.push(name.as_str().to_owned());
         property = match property {
-            Property::String(property) => parse_string_method(name, args).after(property),
-            Property::Boolean(property) => parse_boolean_method(name, args).after(property),
+            Property::String(property) => parse_string_method(name, args)?.after(property),
+            Property::Boolean(property) => parse_boolean_method(name, args)?.after(property),
             Property::CommitOrChangeId(property) => {
-                parse_commit_or_change_id_method(name, args).after(property)
+                parse_commit_or_change_id_method(name, args)?.after(property)
             }
             Property::IdWithHighlightedPrefix(_property) => {
-                panic!("Commit or change ids with styled prefix don't have any methods")
+                return Err(TemplateError::with_pair(
+                    &name,
+                    "commit or change ids with styled prefix don't have any methods",
+                ))
             }
-            Property::Signature(property) => parse_signature_method(name, args).after(property),
-            Property::Timestamp(property) => parse_timestamp_method(name, args).after(property),
+            Property::Signature(property) => parse_signature_method(name, args)?.after(property),
+            Property::Timestamp(property) => parse_timestamp_method(name, args)?.after(property),
+            Property::Integer(property) => parse_integer_method(name, args)?.after(property),
         };
     }
-    PropertyAndLabels(property, labels)
+    Ok(PropertyAndLabels(property, labels))
 }
 
-fn parse_string_method<'a>(name: Pair<Rule>, _args: Pairs<Rule>) -> Property<'a, String> {
+fn parse_string_method<'a>(
+    name: Pair<Rule>,
+    args: Pairs<Rule>,
+) -> TemplateResult<Property<'a, String>> {
     fn wrap_fn<'a, O>(
         f: impl Fn(&String) -> O + 'a,
     ) -> Box<dyn TemplateProperty<String, Output = O> + 'a> {
         Box::new(TemplatePropertyFn(f))
     }
-    // TODO: validate arguments
-    match name.as_str() {
-        "first_line" => Property::String(wrap_fn(|s| s.lines().next().unwrap().to_string())),
-        name => panic!("no such string method: {name}"),
-    }
+    let property = match name.as_str() {
+        "first_line" => {
+            expect_no_arguments(&name, args)?;
+            Property::String(wrap_fn(|s| s.lines().next().unwrap_or("").to_string()))
+        }
+        "upper" => {
+            expect_no_arguments(&name, args)?;
+            Property::String(wrap_fn(|s| s.to_uppercase()))
+        }
+        "lower" => {
+            expect_no_arguments(&name, args)?;
+            Property::String(wrap_fn(|s| s.to_lowercase()))
+        }
+        "substr" => {
+            let mut args = expect_arguments(&name, args, 2)?.into_iter();
+            let start = parse_integer_argument(args.next().unwrap())?;
+            let len = parse_integer_argument(args.next().unwrap())?;
+            let start = start.max(0) as usize;
+            let len = len.max(0) as usize;
+            Property::String(wrap_fn(move |s| {
+                s.chars().skip(start).take(len).collect()
+            }))
+        }
+        "contains" => {
+            let mut args = expect_arguments(&name, args, 1)?.into_iter();
+            let needle = parse_string_argument(args.next().unwrap())?;
+            Property::Boolean(wrap_fn(move |s| s.contains(&needle)))
+        }
+        "len" => {
+            expect_no_arguments(&name, args)?;
+            Property::Integer(wrap_fn(|s| s.chars().count() as i64))
+        }
+        "lines" => {
+            // There's no list property yet, so `lines()` resolves straight to
+            // the number of lines rather than a countable intermediate.
+            expect_no_arguments(&name, args)?;
+            Property::Integer(wrap_fn(|s| s.lines().count() as i64))
+        }
+        _ => {
+            return Err(TemplateError::with_pair(
+                &name,
+                format!("no such method `{}` on string", name.as_str()),
+            ))
+        }
+    };
+    Ok(property)
 }
 
-fn parse_boolean_method<'a>(name: Pair<Rule>, _args: Pairs<Rule>) -> Property<'a, bool> {
-    // TODO: validate arguments
-    panic!("no such boolean method: {}", name.as_str());
+fn parse_boolean_method<'a>(
+    name: Pair<Rule>,
+    _args: Pairs<Rule>,
+) -> TemplateResult<Property<'a, bool>> {
+    Err(TemplateError::with_pair(
+        &name,
+        format!("no such method `{}` on boolean", name.as_str()),
+    ))
 }
 
-fn parse_commit_or_change_id_method<'a>(
+fn parse_integer_method<'a>(
     name: Pair<Rule>,
     _args: Pairs<Rule>,
-) -> Property<'a, CommitOrChangeId<'a>> {
+) -> TemplateResult<Property<'a, i64>> {
+    Err(TemplateError::with_pair(
+        &name,
+        format!("no such method `{}` on integer", name.as_str()),
+    ))
+}
+
+fn parse_commit_or_change_id_method<'a>(
+    name: Pair<Rule>,
+    args: Pairs<Rule>,
+) -> TemplateResult<Property<'a, CommitOrChangeId<'a>>> {
     fn wrap_fn<'a, O>(
         f: impl Fn(&CommitOrChangeId<'a>) -> O + 'a,
     ) -> Box<dyn TemplateProperty<CommitOrChangeId<'a>, Output = O> + 'a> {
         Box::new(TemplatePropertyFn(f))
     }
-    // TODO: validate arguments
-    match name.as_str() {
+    expect_no_arguments(&name, args)?;
+    let property = match name.as_str() {
         "short" => Property::String(wrap_fn(|id| id.short())),
         "shortest_prefix_and_brackets" => {
             Property::String(wrap_fn(|id| id.shortest_prefix_and_brackets()))
@@ -219,43 +470,87 @@ fn parse_commit_or_change_id_method<'a>(
         "shortest_styled_prefix" => {
             Property::IdWithHighlightedPrefix(wrap_fn(|id| id.shortest_styled_prefix()))
         }
-        name => panic!("no such commit ID method: {name}"),
-    }
+        _ => {
+            return Err(TemplateError::with_pair(
+                &name,
+                format!("no such method `{}` on commit id", name.as_str()),
+            ))
+        }
+    };
+    Ok(property)
 }
 
-fn parse_signature_method<'a>(name: Pair<Rule>, _args: Pairs<Rule>) -> Property<'a, Signature> {
+fn parse_signature_method<'a>(
+    name: Pair<Rule>,
+    args: Pairs<Rule>,
+) -> TemplateResult<Property<'a, Signature>> {
     fn wrap_fn<'a, O>(
         f: impl Fn(&Signature) -> O + 'a,
     ) -> Box<dyn TemplateProperty<Signature, Output = O> + 'a> {
         Box::new(TemplatePropertyFn(f))
     }
-    // TODO: validate arguments
-    match name.as_str() {
+    expect_no_arguments(&name, args)?;
+    let property = match name.as_str() {
         "name" => Property::String(wrap_fn(|signature| signature.name.clone())),
         "email" => Property::String(wrap_fn(|signature| signature.email.clone())),
         "timestamp" => Property::Timestamp(wrap_fn(|signature| signature.timestamp.clone())),
-        name => panic!("no such commit ID method: {name}"),
-    }
+        _ => {
+            return Err(TemplateError::with_pair(
+                &name,
+                format!("no such method `{}` on signature", name.as_str()),
+            ))
+        }
+    };
+    Ok(property)
+}
+
+/// Renders a timestamp with a user-supplied strftime-style `format` pattern.
+///
+/// `time_util` only exposes the fixed-form `format_timestamp_relative_to_now`,
+/// so the pattern-based conversion lives here next to its only caller rather
+/// than relying on a helper that doesn't exist in that module.
+fn format_absolute_timestamp(timestamp: &Timestamp, format: &str) -> String {
+    let utc = Utc.timestamp_millis(timestamp.timestamp.0);
+    let tz = FixedOffset::east(timestamp.tz_offset * 60);
+    utc.with_timezone(&tz).format(format).to_string()
 }
 
-fn parse_timestamp_method<'a>(name: Pair<Rule>, _args: Pairs<Rule>) -> Property<'a, Timestamp> {
+fn parse_timestamp_method<'a>(
+    name: Pair<Rule>,
+    args: Pairs<Rule>,
+) -> TemplateResult<Property<'a, Timestamp>> {
     fn wrap_fn<'a, O>(
         f: impl Fn(&Timestamp) -> O + 'a,
     ) -> Box<dyn TemplateProperty<Timestamp, Output = O> + 'a> {
         Box::new(TemplatePropertyFn(f))
     }
-    // TODO: validate arguments
-    match name.as_str() {
-        "ago" => Property::String(wrap_fn(time_util::format_timestamp_relative_to_now)),
-        name => panic!("no such timestamp method: {name}"),
-    }
+    let property = match name.as_str() {
+        "ago" => {
+            expect_no_arguments(&name, args)?;
+            Property::String(wrap_fn(time_util::format_timestamp_relative_to_now))
+        }
+        "format" => {
+            let mut args = expect_arguments(&name, args, 1)?.into_iter();
+            let format = parse_string_argument(args.next().unwrap())?;
+            Property::String(wrap_fn(move |timestamp| {
+                format_absolute_timestamp(timestamp, &format)
+            }))
+        }
+        _ => {
+            return Err(TemplateError::with_pair(
+                &name,
+                format!("no such method `{}` on timestamp", name.as_str()),
+            ))
+        }
+    };
+    Ok(property)
 }
 
 fn parse_commit_keyword<'a>(
     repo: RepoRef<'a>,
     workspace_id: &WorkspaceId,
     pair: Pair<Rule>,
-) -> PropertyAndLabels<'a, Commit> {
+) -> TemplateResult<PropertyAndLabels<'a, Commit>> {
     assert_eq!(pair.as_rule(), Rule::identifier);
     let property = match pair.as_str() {
         "description" => Property::String(Box::new(DescriptionProperty)),
@@ -275,30 +570,45 @@ fn parse_commit_keyword<'a>(
         "divergent" => Property::Boolean(Box::new(DivergentProperty::new(repo))),
         "conflict" => Property::Boolean(Box::new(ConflictProperty)),
         "empty" => Property::Boolean(Box::new(EmptyProperty { repo })),
-        name => panic!("unexpected identifier: {name}"),
+        _ => {
+            return Err(TemplateError::with_pair(
+                &pair,
+                format!("unexpected identifier `{}`", pair.as_str()),
+            ))
+        }
     };
-    PropertyAndLabels(property, vec![pair.as_str().to_string()])
+    Ok(PropertyAndLabels(property, vec![pair.as_str().to_string()]))
 }
 
 fn parse_commit_term<'a>(
     repo: RepoRef<'a>,
     workspace_id: &WorkspaceId,
     pair: Pair<Rule>,
-) -> Expression<'a, Commit> {
+) -> TemplateResult<Expression<'a, Commit>> {
     assert_eq!(pair.as_rule(), Rule::term);
     let mut inner = pair.into_inner();
-    let expr = inner.next().unwrap();
+    let primary = inner.next().unwrap();
     let maybe_method = inner.next().unwrap();
     assert!(inner.next().is_none());
+    assert_eq!(primary.as_rule(), Rule::primary);
+    let expr = primary.into_inner().next().unwrap();
     match expr.as_rule() {
         Rule::literal => {
-            let text = parse_string_literal(expr);
+            let text = parse_string_literal(expr)?;
             let term = PropertyAndLabels(Property::String(Box::new(Literal(text))), vec![]);
-            Expression::Property(parse_method_chain(maybe_method, term))
+            Ok(Expression::Property(parse_method_chain(maybe_method, term)?))
         }
         Rule::identifier => {
-            let term = parse_commit_keyword(repo, workspace_id, expr);
-            Expression::Property(parse_method_chain(maybe_method, term))
+            let term = parse_commit_keyword(repo, workspace_id, expr)?;
+            Ok(Expression::Property(parse_method_chain(maybe_method, term)?))
+        }
+        Rule::integer_literal => {
+            let value: i64 = expr
+                .as_str()
+                .parse()
+                .map_err(|_| TemplateError::with_pair(&expr, "integer literal is out of range"))?;
+            let term = PropertyAndLabels(Property::Integer(Box::new(Literal(value))), vec![]);
+            Ok(Expression::Property(parse_method_chain(maybe_method, term)?))
         }
         Rule::function => {
             let (name, mut args) = {
@@ -311,17 +621,27 @@ fn parse_commit_term<'a>(
             };
             match name.as_str() {
                 "label" => {
-                    let label_pair = args.next().unwrap();
+                    let label_pair = args.next().ok_or_else(|| {
+                        TemplateError::with_pair(&name, "label() requires two arguments")
+                    })?;
                     let label_template =
-                        parse_commit_template_rule(repo, workspace_id, label_pair).into_template();
+                        parse_commit_template_rule(repo, workspace_id, label_pair)?.into_template();
                     let arg_template = match args.next() {
-                        None => panic!("label() requires two arguments"),
+                        None => {
+                            return Err(TemplateError::with_pair(
+                                &name,
+                                "label() requires two arguments",
+                            ))
+                        }
                         Some(pair) => pair,
                     };
                     if args.next().is_some() {
-                        panic!("label() accepts only two arguments")
+                        return Err(TemplateError::with_pair(
+                            &name,
+                            "label() accepts only two arguments",
+                        ));
                     }
-                    let content = parse_commit_template_rule(repo, workspace_id, arg_template)
+                    let content = parse_commit_template_rule(repo, workspace_id, arg_template)?
                         .into_template();
                     let get_labels = move |commit: &Commit| -> Vec<String> {
                         let mut buf = vec![];
@@ -334,72 +654,399 @@ fn parse_commit_term<'a>(
                             .collect()
                     };
                     let template = Box::new(DynamicLabelTemplate::new(content, get_labels));
-                    Expression::Template(template)
+                    Ok(Expression::Template(template))
                 }
                 "if" => {
-                    let condition_pair = args.next().unwrap();
+                    let condition_pair = args.next().ok_or_else(|| {
+                        TemplateError::with_pair(&name, "if() requires at least two arguments")
+                    })?;
+                    let condition_span = condition_pair.as_span();
                     let condition =
-                        parse_commit_template_rule(repo, workspace_id, condition_pair.clone())
+                        parse_commit_template_rule(repo, workspace_id, condition_pair)?
                             .try_into_boolean()
-                            .unwrap_or_else(|| {
-                                panic!("cannot yet use this as boolean: {condition_pair:?}")
-                            });
+                            .ok_or_else(|| {
+                                TemplateError::new(
+                                    condition_span,
+                                    "cannot yet use this as boolean",
+                                )
+                            })?;
 
                     let true_template = match args.next() {
-                        None => panic!("if() requires at least two arguments"),
+                        None => {
+                            return Err(TemplateError::with_pair(
+                                &name,
+                                "if() requires at least two arguments",
+                            ))
+                        }
                         Some(pair) => {
-                            parse_commit_template_rule(repo, workspace_id, pair).into_template()
+                            parse_commit_template_rule(repo, workspace_id, pair)?.into_template()
+                        }
+                    };
+                    let false_template = match args.next() {
+                        None => None,
+                        Some(pair) => {
+                            Some(parse_commit_template_rule(repo, workspace_id, pair)?
+                                .into_template())
                         }
                     };
-                    let false_template = args.next().map(|pair| {
-                        parse_commit_template_rule(repo, workspace_id, pair).into_template()
-                    });
                     if args.next().is_some() {
-                        panic!("if() accepts at most three arguments")
+                        return Err(TemplateError::with_pair(
+                            &name,
+                            "if() accepts at most three arguments",
+                        ));
                     }
                     let template = Box::new(ConditionalTemplate::new(
                         condition,
                         true_template,
                         false_template,
                     ));
-                    Expression::Template(template)
+                    Ok(Expression::Template(template))
                 }
-                name => panic!("function {name} not implemented"),
+                _ => Err(TemplateError::with_pair(
+                    &name,
+                    format!("function `{}` not implemented", name.as_str()),
+                )),
             }
         }
         Rule::template => parse_commit_template_rule(repo, workspace_id, expr),
-        other => panic!("unexpected term: {other:?}"),
+        other => unreachable!("unexpected term: {other:?}"),
     }
 }
 
-fn parse_commit_template_rule<'a>(
+/// Binding power of an infix operator; larger binds more tightly.
+fn binding_power(op: Rule) -> u32 {
+    match op {
+        Rule::or_op => 1,
+        Rule::and_op => 2,
+        Rule::eq_op | Rule::ne_op => 3,
+        Rule::lt_op | Rule::gt_op | Rule::le_op | Rule::ge_op => 4,
+        _ => unreachable!("not an infix operator: {op:?}"),
+    }
+}
+
+fn combine_boolean<'a, C: 'a>(
+    op: Rule,
+    lhs: Box<dyn TemplateProperty<C, Output = bool> + 'a>,
+    rhs: Box<dyn TemplateProperty<C, Output = bool> + 'a>,
+) -> Box<dyn TemplateProperty<C, Output = bool> + 'a> {
+    Box::new(TemplatePropertyFn(move |context: &C| match op {
+        Rule::and_op => lhs.extract(context) && rhs.extract(context),
+        Rule::or_op => lhs.extract(context) || rhs.extract(context),
+        _ => unreachable!("not a boolean operator: {op:?}"),
+    }))
+}
+
+/// Builds a boolean property that compares two operands at format time.
+///
+/// Equality is only defined between operands of the same concrete type;
+/// mismatched or non-comparable operands surface a [`TemplateError`] at the
+/// `span` of the offending operator.
+fn build_equality<'a, C: 'a>(
+    negated: bool,
+    lhs: Property<'a, C>,
+    rhs: Property<'a, C>,
+    span: Span,
+) -> TemplateResult<Box<dyn TemplateProperty<C, Output = bool> + 'a>> {
+    fn compare<'a, C: 'a, T: PartialEq + 'a>(
+        negated: bool,
+        lhs: Box<dyn TemplateProperty<C, Output = T> + 'a>,
+        rhs: Box<dyn TemplateProperty<C, Output = T> + 'a>,
+    ) -> Box<dyn TemplateProperty<C, Output = bool> + 'a> {
+        Box::new(TemplatePropertyFn(move |context: &C| {
+            (lhs.extract(context) == rhs.extract(context)) != negated
+        }))
+    }
+    match (lhs, rhs) {
+        (Property::String(lhs), Property::String(rhs)) => Ok(compare(negated, lhs, rhs)),
+        (Property::Signature(lhs), Property::Signature(rhs)) => Ok(compare(negated, lhs, rhs)),
+        (Property::Timestamp(lhs), Property::Timestamp(rhs)) => Ok(compare(negated, lhs, rhs)),
+        (Property::Integer(lhs), Property::Integer(rhs)) => Ok(compare(negated, lhs, rhs)),
+        _ => Err(TemplateError::new(
+            span,
+            "cannot compare these operands for equality",
+        )),
+    }
+}
+
+/// Builds a boolean property for an ordering comparison between two integers.
+fn build_ordering<'a, C: 'a>(
+    op: Rule,
+    lhs: Property<'a, C>,
+    rhs: Property<'a, C>,
+    span: Span,
+) -> TemplateResult<Box<dyn TemplateProperty<C, Output = bool> + 'a>> {
+    match (lhs, rhs) {
+        (Property::Integer(lhs), Property::Integer(rhs)) => {
+            Ok(Box::new(TemplatePropertyFn(move |context: &C| {
+                let (lhs, rhs) = (lhs.extract(context), rhs.extract(context));
+                match op {
+                    Rule::lt_op => lhs < rhs,
+                    Rule::gt_op => lhs > rhs,
+                    Rule::le_op => lhs <= rhs,
+                    Rule::ge_op => lhs >= rhs,
+                    _ => unreachable!("not an ordering operator: {op:?}"),
+                }
+            })))
+        }
+        _ => Err(TemplateError::new(
+            span,
+            "ordering comparison requires integer operands",
+        )),
+    }
+}
+
+fn apply_operator<'a>(
+    op: Pair<Rule>,
+    lhs: Expression<'a, Commit>,
+    rhs: Expression<'a, Commit>,
+) -> TemplateResult<Expression<'a, Commit>> {
+    let span = op.as_span();
+    match op.as_rule() {
+        Rule::and_op | Rule::or_op => {
+            let lhs = lhs
+                .try_into_boolean()
+                .ok_or_else(|| TemplateError::new(span.clone(), "operand is not a boolean"))?;
+            let rhs = rhs
+                .try_into_boolean()
+                .ok_or_else(|| TemplateError::new(span.clone(), "operand is not a boolean"))?;
+            Ok(boolean_expression(combine_boolean(op.as_rule(), lhs, rhs)))
+        }
+        Rule::eq_op | Rule::ne_op => {
+            let negated = op.as_rule() == Rule::ne_op;
+            let lhs = lhs
+                .try_into_property()
+                .ok_or_else(|| TemplateError::new(span.clone(), "operand is not comparable"))?;
+            let rhs = rhs
+                .try_into_property()
+                .ok_or_else(|| TemplateError::new(span.clone(), "operand is not comparable"))?;
+            Ok(boolean_expression(build_equality(negated, lhs, rhs, span)?))
+        }
+        op @ (Rule::lt_op | Rule::gt_op | Rule::le_op | Rule::ge_op) => {
+            let lhs = lhs
+                .try_into_property()
+                .ok_or_else(|| TemplateError::new(span.clone(), "operand is not comparable"))?;
+            let rhs = rhs
+                .try_into_property()
+                .ok_or_else(|| TemplateError::new(span.clone(), "operand is not comparable"))?;
+            Ok(boolean_expression(build_ordering(op, lhs, rhs, span)?))
+        }
+        other => unreachable!("not an infix operator: {other:?}"),
+    }
+}
+
+fn parse_list<'a>(
     repo: RepoRef<'a>,
     workspace_id: &WorkspaceId,
     pair: Pair<Rule>,
-) -> Expression<'a, Commit> {
-    assert_eq!(pair.as_rule(), Rule::template);
-    let inner = pair.into_inner();
-    let mut expressions = inner
+) -> TemplateResult<Expression<'a, Commit>> {
+    assert_eq!(pair.as_rule(), Rule::list);
+    let mut expressions = pair
+        .into_inner()
         .map(|term| parse_commit_term(repo, workspace_id, term))
-        .collect_vec();
+        .collect::<TemplateResult<Vec<_>>>()?;
     if expressions.len() == 1 {
-        expressions.pop().unwrap()
+        Ok(expressions.pop().unwrap())
     } else {
         let templates = expressions.into_iter().map(|x| x.into_template()).collect();
-        Expression::Template(Box::new(ListTemplate(templates)))
+        Ok(Expression::Template(Box::new(ListTemplate(templates))))
+    }
+}
+
+fn parse_not_expr<'a>(
+    repo: RepoRef<'a>,
+    workspace_id: &WorkspaceId,
+    pair: Pair<Rule>,
+) -> TemplateResult<Expression<'a, Commit>> {
+    assert_eq!(pair.as_rule(), Rule::not_expr);
+    let span = pair.as_span();
+    let mut prefixes = 0;
+    let mut list_pair = None;
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::prefix_op => prefixes += 1,
+            Rule::list => list_pair = Some(inner),
+            other => unreachable!("unexpected not_expr part: {other:?}"),
+        }
+    }
+    let expression = parse_list(repo, workspace_id, list_pair.unwrap())?;
+    if prefixes == 0 {
+        return Ok(expression);
+    }
+    let mut property = expression
+        .try_into_boolean()
+        .ok_or_else(|| TemplateError::new(span, "cannot negate a non-boolean operand"))?;
+    for _ in 0..prefixes {
+        let inner = property;
+        property = Box::new(TemplatePropertyFn(move |context: &Commit| {
+            !inner.extract(context)
+        }));
+    }
+    Ok(boolean_expression(property))
+}
+
+fn parse_commit_template_rule<'a>(
+    repo: RepoRef<'a>,
+    workspace_id: &WorkspaceId,
+    pair: Pair<Rule>,
+) -> TemplateResult<Expression<'a, Commit>> {
+    assert_eq!(pair.as_rule(), Rule::template);
+    // Flatten the alternating `not_expr (op not_expr)*` sequence, then fold it
+    // with a precedence-climbing pass so that e.g. `a || b && c` binds as
+    // `a || (b && c)` and `==`/`!=` bind tighter than both.
+    let mut operands = Vec::new();
+    let mut operators = Vec::new();
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::not_expr => operands.push(parse_not_expr(repo, workspace_id, inner)?),
+            _ => operators.push(inner),
+        }
+    }
+    let mut operands = operands.into_iter();
+    let lhs = operands.next().unwrap();
+    climb_operators(lhs, &mut operators.into_iter().peekable(), &mut operands, 0)
+}
+
+fn climb_operators<'a>(
+    mut lhs: Expression<'a, Commit>,
+    operators: &mut std::iter::Peekable<std::vec::IntoIter<Pair<Rule>>>,
+    operands: &mut std::vec::IntoIter<Expression<'a, Commit>>,
+    min_power: u32,
+) -> TemplateResult<Expression<'a, Commit>> {
+    while let Some(op) = operators.peek() {
+        let power = binding_power(op.as_rule());
+        if power < min_power {
+            break;
+        }
+        let op = operators.next().unwrap();
+        let rhs = operands.next().unwrap();
+        // Left-associative: only recurse into operators that bind more tightly.
+        let rhs = climb_operators(rhs, operators, operands, power + 1)?;
+        lhs = apply_operator(op, lhs, rhs)?;
     }
+    Ok(lhs)
 }
 
 pub fn parse_commit_template<'a>(
     repo: RepoRef<'a>,
     workspace_id: &WorkspaceId,
     template_text: &str,
-) -> Box<dyn Template<Commit> + 'a> {
-    let mut pairs: Pairs<Rule> = TemplateParser::parse(Rule::program, template_text).unwrap();
+) -> TemplateResult<Box<dyn Template<Commit> + 'a>> {
+    let mut pairs: Pairs<Rule> = TemplateParser::parse(Rule::program, template_text)?;
     let first_pair = pairs.next().unwrap();
     if first_pair.as_rule() == Rule::EOI {
-        Box::new(Literal(String::new()))
+        Ok(Box::new(Literal(String::new())))
     } else {
-        parse_commit_template_rule(repo, workspace_id, first_pair).into_template()
+        Ok(parse_commit_template_rule(repo, workspace_id, first_pair)?.into_template())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a quoted literal and runs it through [`parse_string_literal`].
+    fn parse_literal(source: &str) -> TemplateResult<String> {
+        let pair = TemplateParser::parse(Rule::literal, source)
+            .unwrap()
+            .next()
+            .unwrap();
+        parse_string_literal(pair)
+    }
+
+    /// Seeds a string property with `input` and applies the method chain in
+    /// `chain` (e.g. `".upper()"`), returning the resulting property. Because
+    /// the seed is a [`Literal`], the extraction context is irrelevant.
+    fn apply_methods(input: &str, chain: &str) -> Property<'static, String> {
+        let pair = TemplateParser::parse(Rule::maybe_method, chain)
+            .unwrap()
+            .next()
+            .unwrap();
+        let seed = PropertyAndLabels(
+            Property::String(Box::new(Literal(input.to_string()))),
+            vec![],
+        );
+        parse_method_chain(pair, seed).unwrap().0
+    }
+
+    fn as_string(property: Property<'static, String>) -> String {
+        match property {
+            Property::String(p) => p.extract(&String::new()),
+            _ => panic!("expected a string property"),
+        }
+    }
+
+    fn as_integer(property: Property<'static, String>) -> i64 {
+        match property {
+            Property::Integer(p) => p.extract(&String::new()),
+            _ => panic!("expected an integer property"),
+        }
+    }
+
+    fn as_boolean(property: Property<'static, String>) -> bool {
+        match property {
+            Property::Boolean(p) => p.extract(&String::new()),
+            _ => panic!("expected a boolean property"),
+        }
+    }
+
+    #[test]
+    fn string_literal_decodes_escapes() {
+        assert_eq!(parse_literal(r#""plain""#).unwrap(), "plain");
+        assert_eq!(parse_literal(r#""a\nb""#).unwrap(), "a\nb");
+        assert_eq!(parse_literal(r#""a\"b\\c""#).unwrap(), "a\"b\\c");
+    }
+
+    #[test]
+    fn first_line_of_empty_string_does_not_panic() {
+        assert_eq!(as_string(apply_methods("", ".first_line()")), "");
+        assert_eq!(
+            as_string(apply_methods("first\nsecond", ".first_line()")),
+            "first"
+        );
+    }
+
+    #[test]
+    fn upper_and_lower() {
+        assert_eq!(as_string(apply_methods("MixedCase", ".upper()")), "MIXEDCASE");
+        assert_eq!(as_string(apply_methods("MixedCase", ".lower()")), "mixedcase");
+    }
+
+    #[test]
+    fn substr_slices_by_character() {
+        assert_eq!(as_string(apply_methods("hello", ".substr(0, 2)")), "he");
+        assert_eq!(as_string(apply_methods("hello", ".substr(1, 3)")), "ell");
+        assert_eq!(as_string(apply_methods("hi", ".substr(0, 10)")), "hi");
+    }
+
+    #[test]
+    fn contains_reports_substring() {
+        assert!(as_boolean(apply_methods("hello world", ".contains(\"world\")")));
+        assert!(!as_boolean(apply_methods("hello", ".contains(\"world\")")));
+    }
+
+    #[test]
+    fn len_counts_characters() {
+        assert_eq!(as_integer(apply_methods("hello", ".len()")), 5);
+        assert_eq!(as_integer(apply_methods("", ".len()")), 0);
+    }
+
+    #[test]
+    fn lines_counts_lines_not_words() {
+        assert_eq!(as_integer(apply_methods("line one\nline two", ".lines()")), 2);
+        assert_eq!(as_integer(apply_methods("single line", ".lines()")), 1);
+        assert_eq!(as_integer(apply_methods("", ".lines()")), 0);
+    }
+
+    #[test]
+    fn unknown_method_is_an_error() {
+        let pair = TemplateParser::parse(Rule::maybe_method, ".shortt()")
+            .unwrap()
+            .next()
+            .unwrap();
+        let seed = PropertyAndLabels(
+            Property::String(Box::new(Literal("x".to_string()))),
+            vec![],
+        );
+        assert!(parse_method_chain(pair, seed).is_err());
     }
 }